@@ -1,12 +1,18 @@
+mod storage;
+
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::path::{Path, PathBuf};
-use tokio::fs::File;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use rocket::form::Form;
 use rocket::fs::TempFile;
 use rocket_db_pools::{Connection, Database};
 
-use sqlx::Error as SqlError;
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, Error as SqlError};
 use uuid::Uuid;
 
 use rocket::fairing::AdHoc;
@@ -20,10 +26,90 @@ use rocket::http::uri::Absolute;
 use rocket::{catch, catchers, get, launch, post, routes, uri, FromForm, State};
 use serde::Deserialize;
 
+use storage::{LocalFs, StorageBackend, StorageConfig, S3};
+
 #[derive(Deserialize)]
 struct AppConfig<'a> {
     file_path: PathBuf,
     base_url: Absolute<'a>,
+    #[serde(default)]
+    default_ttl_secs: Option<i64>,
+    #[serde(default = "default_reaper_interval_secs")]
+    reaper_interval_secs: u64,
+    #[serde(default)]
+    storage: StorageConfig,
+    #[serde(default)]
+    max_file_size: Option<u64>,
+    #[serde(default)]
+    max_total_bytes_per_ip: Option<u64>,
+}
+
+fn default_reaper_interval_secs() -> u64 {
+    60
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// `expiry` values at or above this are treated as an absolute Unix
+/// timestamp rather than a duration in seconds. No sane TTL runs past ~31
+/// years, but any real absolute expiry for the foreseeable future clears it.
+const ABSOLUTE_EXPIRY_THRESHOLD: i64 = 1_000_000_000;
+
+/// Resolves an `expiry` value (from [`AppConfig::default_ttl_secs`], the
+/// `/raw` query, or [`FileDescriptorForm`]) into a `valid_till` timestamp,
+/// accepting either a duration in seconds from now or an absolute Unix
+/// timestamp.
+fn resolve_expiry(expiry: i64) -> i64 {
+    if expiry >= ABSOLUTE_EXPIRY_THRESHOLD {
+        expiry
+    } else {
+        now_unix() + expiry
+    }
+}
+
+/// `sniff_content_type`/`infer` only ever look at the first few hundred
+/// bytes, so this is plenty to sniff from without holding onto more of the
+/// upload than necessary.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Streams `file` in fixed-size chunks, feeding them to a SHA-256 hasher, and
+/// returns the resulting digest alongside the leading [`SNIFF_BYTES`] for
+/// content-type sniffing. Reads the temp file rather than `read_to_end`-ing it
+/// into one big buffer so a large upload doesn't have to fit in memory twice
+/// over (once here, once again if the backend buffers its own copy).
+async fn hash_file(file: &mut TempFile<'_>) -> Result<(String, Vec<u8>), std::io::Error> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let mut reader = file.open().await?;
+    let mut hasher = Sha256::new();
+    let mut sniff = Vec::with_capacity(SNIFF_BYTES);
+    let mut buf = [0u8; 64 * 1024];
+
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        if sniff.len() < SNIFF_BYTES {
+            let take = (SNIFF_BYTES - sniff.len()).min(n);
+            sniff.extend_from_slice(&buf[..take]);
+        }
+    }
+
+    Ok((format!("{:x}", hasher.finalize()), sniff))
+}
+
+fn ip_to_bytes(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V6(a) => a.octets().to_vec(),
+        IpAddr::V4(a) => a.octets().to_vec(),
+    }
 }
 
 fn bytes_to_ip(bytes: Vec<u8>) -> Option<IpAddr> {
@@ -45,9 +131,27 @@ fn bytes_to_ip(bytes: Vec<u8>) -> Option<IpAddr> {
     }
 }
 
+/// Sniffs an upload's real MIME type from its magic bytes, falling back to
+/// whatever content type the client declared and finally to a generic binary
+/// blob. Magic bytes win over the declared type since clients regularly lie
+/// (e.g. browsers defaulting everything to `application/octet-stream`).
+fn sniff_content_type(declared: Option<&ContentType>, bytes: &[u8]) -> String {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type().to_string();
+    }
+
+    if let Some(ct) = declared {
+        if !ct.is_any() {
+            return format!("{}/{}", ct.top(), ct.sub());
+        }
+    }
+
+    "application/octet-stream".to_string()
+}
+
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy)]
-struct FileDescriptor(Uuid);
+pub(crate) struct FileDescriptor(Uuid);
 
 impl From<Uuid> for FileDescriptor {
     fn from(uuid: Uuid) -> Self {
@@ -102,33 +206,225 @@ impl FromUriParam<UriPath, FileDescriptor> for FileDescriptor {
     }
 }
 
-struct FileDownload(File, FileInfo);
+/// Upload previews are only worth buffering for text: past this size we just
+/// stream the blob straight through as an attachment.
+const INLINE_TEXT_LIMIT: u64 = 512 * 1024;
+
+fn is_previewable_image(content_type: &str) -> bool {
+    matches!(
+        content_type,
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp" | "image/x-icon"
+    )
+}
+
+/// Text types safe to render inline. Deliberately an allowlist rather than
+/// "anything `text/*`": `text/html` (and friends) executes as a page on this
+/// origin if served inline, turning every upload endpoint into stored XSS.
+fn is_previewable_text(content_type: &str) -> bool {
+    matches!(content_type, "text/plain" | "text/csv")
+}
+
+/// Whether and how a download should render in the browser instead of
+/// prompting to save.
+enum Preview {
+    Inline(ContentType),
+    Attachment,
+}
+
+struct FileDownload {
+    reader: Box<dyn rocket::tokio::io::AsyncRead + Send + Unpin>,
+    info: FileInfo,
+    preview: Preview,
+}
 
 impl FileDownload {
-    pub async fn open(fd: FileInfo, file_path: &Path) -> std::io::Result<Self> {
-        let path = file_path.join(fd.fd.to_string());
-        let file = File::open(path).await?;
-        Ok(Self(file, fd))
+    pub async fn open(
+        fd: FileInfo,
+        backend: &dyn StorageBackend,
+        force_download: bool,
+    ) -> Result<Option<Self>, storage::StorageError> {
+        use rocket::tokio::io::AsyncReadExt;
+
+        let reader = match backend.get(&fd.content_hash).await? {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+
+        let (reader, preview) = if is_previewable_text(&fd.content_type) {
+            let mut limited = reader.take(INLINE_TEXT_LIMIT + 1);
+            let mut head = Vec::new();
+            limited.read_to_end(&mut head).await?;
+            let rest = limited.into_inner();
+
+            let fits = (head.len() as u64) <= INLINE_TEXT_LIMIT;
+            let is_utf8 = std::str::from_utf8(&head).is_ok();
+
+            let chained: Box<dyn rocket::tokio::io::AsyncRead + Send + Unpin> =
+                Box::new(std::io::Cursor::new(head).chain(rest));
+
+            let preview = if !fits {
+                Preview::Attachment
+            } else if is_utf8 {
+                Preview::Inline(
+                    ContentType::parse_flexible(&fd.content_type).unwrap_or(ContentType::Binary),
+                )
+            } else {
+                // Declared text/* but not actually UTF-8: still safe to render
+                // inline as long as the browser treats it as opaque bytes.
+                Preview::Inline(ContentType::new("application", "octet-stream"))
+            };
+
+            (chained, preview)
+        } else if is_previewable_image(&fd.content_type) {
+            let content_type =
+                ContentType::parse_flexible(&fd.content_type).unwrap_or(ContentType::Binary);
+            (reader, Preview::Inline(content_type))
+        } else {
+            (reader, Preview::Attachment)
+        };
+
+        let preview = if force_download { Preview::Attachment } else { preview };
+
+        Ok(Some(Self {
+            reader,
+            info: fd,
+            preview,
+        }))
     }
 }
 
 impl<'r> Responder<'r, 'static> for FileDownload {
     fn respond_to(self, r: &'r Request<'_>) -> response::Result<'static> {
-        let mut res = self.0.respond_to(r)?;
+        let mut res = rocket::response::stream::ReaderStream::one(self.reader).respond_to(r)?;
 
-        res.set_header(ContentType::Binary);
+        let (content_type, disposition) = match self.preview {
+            Preview::Inline(ct) => (ct, "inline"),
+            Preview::Attachment => (ContentType::Binary, "attachment"),
+        };
+        res.set_header(content_type);
 
-        let filename = self.1.name.unwrap_or_else(|| self.1.fd.to_string());
+        if disposition == "inline" {
+            // Belt and suspenders on top of the inline allowlist: never let a
+            // browser second-guess the declared type into something it'll
+            // execute (e.g. sniffing a mislabeled upload as `text/html`).
+            res.set_header(Header::new("X-Content-Type-Options", "nosniff"));
+        }
 
+        let filename = self.info.name.unwrap_or_else(|| self.info.fd.to_string());
         res.set_header(Header::new(
             "Content-Disposition",
-            format!("attachment; filename=\"{}\"", filename),
+            format!("{}; filename=\"{}\"", disposition, filename),
         ));
 
         Ok(res)
     }
 }
 
+/// Wraps a [`FileDownload`]'s reader for one-time links: once the underlying
+/// read reaches EOF, fires off `burn_file` to drop the fd's row and (if it
+/// was the last reference) the blob. Runs as a detached task rather than
+/// inline in `poll_read` because by the time the last byte is polled the
+/// response has already started streaming to the client.
+struct BurnAfterReading {
+    inner: Box<dyn rocket::tokio::io::AsyncRead + Send + Unpin>,
+    pool: rocket_db_pools::sqlx::SqlitePool,
+    backend: Arc<dyn StorageBackend>,
+    fd: FileDescriptor,
+    burned: bool,
+}
+
+impl BurnAfterReading {
+    fn new(
+        inner: Box<dyn rocket::tokio::io::AsyncRead + Send + Unpin>,
+        pool: rocket_db_pools::sqlx::SqlitePool,
+        backend: Arc<dyn StorageBackend>,
+        fd: FileDescriptor,
+    ) -> Self {
+        Self {
+            inner,
+            pool,
+            backend,
+            fd,
+            burned: false,
+        }
+    }
+}
+
+impl rocket::tokio::io::AsyncRead for BurnAfterReading {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut rocket::tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = res {
+            if buf.filled().len() == before && !self.burned {
+                self.burned = true;
+                let pool = self.pool.clone();
+                let backend = self.backend.clone();
+                let fd = self.fd;
+                tokio::spawn(async move {
+                    burn_file(&pool, fd, backend.as_ref()).await;
+                });
+            }
+        }
+
+        res
+    }
+}
+
+/// Deletes a one-time-download fd's row and releases its blob, guarding
+/// against a concurrent request burning the same fd twice: the `DELETE`
+/// only affects a row if one is still there, so a losing racer's release
+/// is a no-op.
+async fn burn_file(pool: &rocket_db_pools::sqlx::SqlitePool, fd: FileDescriptor, backend: &dyn StorageBackend) {
+    let f = fd.as_ref();
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("burn: failed to start transaction for {}: {}", f, e);
+            return;
+        }
+    };
+
+    let hash = match sqlx::query_scalar!(
+        "DELETE FROM file_index WHERE fd = ?1 RETURNING content_hash",
+        f
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("burn: failed to delete row for {}: {}", f, e);
+            return;
+        }
+    };
+
+    let refcount = match release_blob(&mut tx, &hash).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("burn: failed to release blob {} for {}: {}", hash, f, e);
+            return;
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("burn: failed to commit deletion of {}: {}", f, e);
+        return;
+    }
+
+    if refcount <= 0 {
+        if let Err(e) = backend.delete(&hash).await {
+            eprintln!("burn: failed to unlink blob {}: {}", hash, e);
+        }
+    }
+}
+
 #[derive(Database)]
 #[database("file_index")]
 struct FileIndex(rocket_db_pools::sqlx::SqlitePool);
@@ -137,16 +433,39 @@ struct FileInfo {
     fd: FileDescriptor,
     name: Option<String>,
     upload_ip: IpAddr,
+    valid_till: Option<i64>,
+    content_hash: String,
+    content_type: String,
+    one_time: bool,
+    size: i64,
 }
 
 impl FileInfo {
-    pub fn new(fd: FileDescriptor, name: Option<String>, upload_ip: IpAddr) -> Self {
+    pub fn new(
+        fd: FileDescriptor,
+        name: Option<String>,
+        upload_ip: IpAddr,
+        valid_till: Option<i64>,
+        content_hash: String,
+        content_type: String,
+        one_time: bool,
+        size: i64,
+    ) -> Self {
         Self {
             fd,
             name,
             upload_ip,
+            valid_till,
+            content_hash,
+            content_type,
+            one_time,
+            size,
         }
     }
+
+    fn is_expired(&self) -> bool {
+        matches!(self.valid_till, Some(t) if t < now_unix())
+    }
 }
 
 async fn get_file(
@@ -154,9 +473,12 @@ async fn get_file(
     fd: FileDescriptor,
 ) -> Result<Option<FileInfo>, SqlError> {
     let f = fd.as_ref();
-    let info = match sqlx::query!("SELECT name, upload_ip FROM file_index WHERE fd = ?1", f)
-        .fetch_one(&mut *db)
-        .await
+    let info = match sqlx::query!(
+        "SELECT name, upload_ip, valid_till, content_hash, content_type, one_time, size FROM file_index WHERE fd = ?1",
+        f
+    )
+    .fetch_one(&mut *db)
+    .await
     {
         Ok(i) => i,
         Err(SqlError::RowNotFound) => return Ok(None),
@@ -169,37 +491,317 @@ async fn get_file(
         None => return Ok(None),
     };
 
-    Ok(Some(FileInfo::new(fd, name, addr)))
+    let file = FileInfo::new(
+        fd,
+        name,
+        addr,
+        info.valid_till,
+        info.content_hash,
+        info.content_type,
+        info.one_time,
+        info.size,
+    );
+    if file.is_expired() {
+        return Ok(None);
+    }
+
+    Ok(Some(file))
 }
 
-async fn add_file(mut db: Connection<FileIndex>, info: FileInfo) -> Result<(), FileError> {
+/// Sum of `size` across every non-expired row uploaded by `addr`, used to
+/// enforce [`AppConfig::max_total_bytes_per_ip`] before a new upload lands.
+async fn total_upload_bytes(db: &mut Connection<FileIndex>, addr: IpAddr) -> Result<i64, SqlError> {
+    let upload_ip = ip_to_bytes(addr);
+    let now = now_unix();
+    let total = sqlx::query_scalar!(
+        "SELECT COALESCE(SUM(size), 0) FROM file_index WHERE upload_ip = ?1 AND (valid_till IS NULL OR valid_till >= ?2)",
+        upload_ip,
+        now
+    )
+    .fetch_one(&mut **db)
+    .await?;
+
+    Ok(total)
+}
+
+async fn add_file(db: &mut Connection<FileIndex>, info: &FileInfo) -> Result<(), FileError> {
     let f = info.fd.as_ref();
-    let upload_ip = match info.upload_ip {
-        IpAddr::V6(a) => a.octets().to_vec(),
-        IpAddr::V4(a) => a.octets().to_vec(),
-    };
+    let upload_ip = ip_to_bytes(info.upload_ip);
     sqlx::query!(
-        "INSERT INTO file_index (fd, name, upload_ip) VALUES (?1, ?2, ?3)",
+        "INSERT INTO file_index (fd, name, upload_ip, valid_till, content_hash, content_type, one_time, size) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
         f,
         info.name,
-        upload_ip
+        upload_ip,
+        info.valid_till,
+        info.content_hash,
+        info.content_type,
+        info.one_time,
+        info.size
     )
-    .execute(&mut *db)
+    .execute(&mut **db)
     .await?;
 
     Ok(())
 }
 
+/// Re-checks [`AppConfig::max_total_bytes_per_ip`] and inserts `info`'s row
+/// in one `BEGIN IMMEDIATE` transaction. `upload_file`'s earlier check is
+/// only a fast rejection for obviously-over-quota uploads and runs *before*
+/// hashing to avoid wasting that work; it reads and writes in separate
+/// steps, so two concurrent uploads from the same IP could each see the
+/// pre-upload total and together exceed the cap. `BEGIN IMMEDIATE` takes
+/// SQLite's write lock up front, so this is the check that actually holds.
+async fn add_file_with_quota(
+    db: &mut Connection<FileIndex>,
+    info: FileInfo,
+    max_total_bytes_per_ip: Option<u64>,
+) -> Result<(), FileError> {
+    sqlx::query("BEGIN IMMEDIATE").execute(&mut **db).await?;
+
+    let result: Result<(), FileError> = async {
+        if let Some(max_total) = max_total_bytes_per_ip {
+            let used = total_upload_bytes(db, info.upload_ip).await? as u64;
+            if used.saturating_add(info.size as u64) > max_total {
+                return Err(FileError::quota_exceeded());
+            }
+        }
+
+        add_file(db, &info).await
+    }
+    .await;
+
+    if result.is_ok() {
+        sqlx::query("COMMIT").execute(&mut **db).await?;
+    } else {
+        let _ = sqlx::query("ROLLBACK").execute(&mut **db).await;
+    }
+
+    result
+}
+
+/// Registers a reference to `hash` in the `blobs` table, writing the blob to
+/// `backend` only if this is the first reference. Runs in its own
+/// transaction so concurrent uploads of the same content can't double-write
+/// the blob or leak a refcount bump.
+async fn ensure_blob(
+    db: &mut Connection<FileIndex>,
+    hash: &str,
+    reader: &mut (dyn rocket::tokio::io::AsyncRead + Send + Unpin),
+    backend: &dyn StorageBackend,
+) -> Result<(), FileError> {
+    let mut tx = db.begin().await?;
+
+    let inserted = sqlx::query!(
+        "INSERT INTO blobs (hash, refcount) VALUES (?1, 1) ON CONFLICT(hash) DO NOTHING",
+        hash
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if inserted.rows_affected() == 0 {
+        sqlx::query!(
+            "UPDATE blobs SET refcount = refcount + 1 WHERE hash = ?1",
+            hash
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    // Write the blob *before* committing: if `backend.put` fails, the
+    // transaction rolls back the insert with it, so a disk-full or S3
+    // hiccup can't leave a `blobs` row claiming content that was never
+    // actually written (which would make every future upload of the same
+    // bytes bump a refcount onto a blob that doesn't exist).
+    backend.put(hash, reader).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Drops a reference to `hash` held by `tx`, returning the refcount left
+/// behind. The caller is responsible for unlinking the blob from storage
+/// once the count reaches zero, once its transaction has committed.
+async fn release_blob(
+    tx: &mut sqlx::Transaction<'_, rocket_db_pools::sqlx::Sqlite>,
+    hash: &str,
+) -> Result<i64, SqlError> {
+    let refcount = sqlx::query_scalar!(
+        "UPDATE blobs SET refcount = refcount - 1 WHERE hash = ?1 RETURNING refcount",
+        hash
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if refcount <= 0 {
+        sqlx::query!("DELETE FROM blobs WHERE hash = ?1", hash)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(refcount)
+}
+
+/// Undoes an `ensure_blob` call whose `file_index` row never ended up
+/// landing (e.g. a quota-rejected upload): drops the reference `ensure_blob`
+/// took and unlinks the blob from `backend` if that was the last one.
+/// Without this, every quota-rejected upload of novel content would leave
+/// its freshly-written blob permanently unreferenced and unreaped.
+async fn release_orphaned_blob(
+    db: &mut Connection<FileIndex>,
+    hash: &str,
+    backend: &dyn StorageBackend,
+) -> Result<(), FileError> {
+    let mut tx = db.begin().await?;
+    let refcount = release_blob(&mut tx, hash).await?;
+    tx.commit().await?;
+
+    if refcount <= 0 {
+        backend.delete(hash).await?;
+    }
+
+    Ok(())
+}
+
+async fn reap_expired(pool: &rocket_db_pools::sqlx::SqlitePool, backend: &dyn StorageBackend) {
+    let now = now_unix();
+    let expired = match sqlx::query!("SELECT fd FROM file_index WHERE valid_till < ?1", now)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("reaper: failed to query expired files: {}", e);
+            return;
+        }
+    };
+
+    for row in expired {
+        let fd_str = row.fd;
+
+        let mut tx = match pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                eprintln!("reaper: failed to start transaction: {}", e);
+                continue;
+            }
+        };
+
+        // `RETURNING` + `fetch_optional` rather than a blind `execute`: if a
+        // concurrent `burn_file` (one-time download completing) already
+        // deleted this fd, zero rows match and we must not also release its
+        // blob reference, which could still be legitimately held by another,
+        // unexpired fd sharing the same `content_hash`.
+        let hash = match sqlx::query_scalar!(
+            "DELETE FROM file_index WHERE fd = ?1 RETURNING content_hash",
+            fd_str
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        {
+            Ok(Some(hash)) => hash,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("reaper: failed to delete row for {}: {}", fd_str, e);
+                continue;
+            }
+        };
+
+        let refcount = match release_blob(&mut tx, &hash).await {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("reaper: failed to release blob {} for {}: {}", hash, fd_str, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = tx.commit().await {
+            eprintln!("reaper: failed to commit deletion of {}: {}", fd_str, e);
+            continue;
+        }
+
+        if refcount <= 0 {
+            if let Err(e) = backend.delete(&hash).await {
+                eprintln!("reaper: failed to unlink blob {}: {}", hash, e);
+            }
+        }
+    }
+}
+
+fn reaper_fairing() -> AdHoc {
+    AdHoc::on_liftoff("Expired Upload Reaper", |rocket| {
+        Box::pin(async move {
+            let pool = match rocket.state::<FileIndex>() {
+                Some(index) => index.0.clone(),
+                None => {
+                    eprintln!("reaper: FileIndex not managed, not starting reaper");
+                    return;
+                }
+            };
+            let backend = match rocket.state::<Arc<dyn StorageBackend>>() {
+                Some(b) => b.clone(),
+                None => {
+                    eprintln!("reaper: storage backend not managed, not starting reaper");
+                    return;
+                }
+            };
+            let config = match rocket.state::<AppConfig<'static>>() {
+                Some(c) => c,
+                None => {
+                    eprintln!("reaper: AppConfig not managed, not starting reaper");
+                    return;
+                }
+            };
+            let interval = Duration::from_secs(config.reaper_interval_secs);
+
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    reap_expired(&pool, backend.as_ref()).await;
+                }
+            });
+        })
+    })
+}
+
+fn storage_fairing() -> AdHoc {
+    AdHoc::on_ignite("Storage Backend", |rocket| {
+        Box::pin(async move {
+            let config: AppConfig<'static> = match rocket.figment().extract() {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("storage: failed to read config: {}", e);
+                    return rocket;
+                }
+            };
+
+            let backend: Arc<dyn StorageBackend> = match config.storage {
+                StorageConfig::LocalFs => Arc::new(LocalFs::new(config.file_path)),
+                StorageConfig::S3(s3_config) => Arc::new(S3::new(&s3_config).await),
+            };
+
+            rocket.manage(backend)
+        })
+    })
+}
+
 #[derive(Responder)]
 enum FileError {
     #[response(status = 500)]
     SqlError(&'static str, #[response(ignore)] SqlError),
     #[response(status = 500)]
     IoError(&'static str, #[response(ignore)] std::io::Error),
+    #[response(status = 500)]
+    StorageError(&'static str, #[response(ignore)] storage::StorageError),
     #[response(status = 400)]
     Uuid(&'static str),
     #[response(status = 422)]
     InvalidForm(&'static str),
+    #[response(status = 413)]
+    TooLarge(&'static str),
+    #[response(status = 429)]
+    QuotaExceeded(&'static str),
 }
 
 impl FileError {
@@ -212,6 +814,18 @@ impl FileError {
             "EINVAL: invalid argument\n// You are probably lost. Try and go back to the start.\n",
         )
     }
+
+    fn too_large() -> Self {
+        Self::TooLarge(
+            "ENOSPC: No space left on device\n// Maybe it's time to get a bigger hard drive?\n",
+        )
+    }
+
+    fn quota_exceeded() -> Self {
+        Self::QuotaExceeded(
+            "EDQUOT: Disk quota exceeded\n// You've used up your allotted slice of the disk. Delete something or wait for it to expire.\n",
+        )
+    }
 }
 
 impl From<std::io::Error> for FileError {
@@ -232,39 +846,83 @@ impl From<SqlError> for FileError {
     }
 }
 
+impl From<storage::StorageError> for FileError {
+    fn from(e: storage::StorageError) -> Self {
+        Self::StorageError(
+            "EREMOTEIO: Remote I/O error\n// The storage backend had a bad day.\n",
+            e,
+        )
+    }
+}
+
 async fn start_file_download(
     db: Connection<FileIndex>,
+    pool: rocket_db_pools::sqlx::SqlitePool,
     fd: FileDescriptor,
     name: Option<String>,
-    file_path: &Path,
+    backend: Arc<dyn StorageBackend>,
+    force_download: bool,
 ) -> Result<Option<FileDownload>, FileError> {
     Ok(if let Some(mut file) = get_file(db, fd).await? {
         if let Some(n) = name {
             file.name = Some(n);
         }
-        Some(FileDownload::open(file, file_path).await?)
+        let one_time = file.one_time;
+
+        match FileDownload::open(file, backend.as_ref(), force_download).await? {
+            Some(mut download) if one_time => {
+                download.reader = Box::new(BurnAfterReading::new(
+                    download.reader,
+                    pool,
+                    backend,
+                    fd,
+                ));
+                Some(download)
+            }
+            other => other,
+        }
     } else {
         None
     })
 }
 
-#[get("/fd/<fd>")]
+#[get("/fd/<fd>?<download>")]
 async fn download_file(
     db: Connection<FileIndex>,
+    index: &State<FileIndex>,
     fd: FileDescriptor,
-    config: &State<AppConfig<'_>>,
+    download: Option<bool>,
+    backend: &State<Arc<dyn StorageBackend>>,
 ) -> Result<Option<FileDownload>, FileError> {
-    start_file_download(db, fd, None, &config.file_path).await
+    start_file_download(
+        db,
+        index.0.clone(),
+        fd,
+        None,
+        backend.inner().clone(),
+        download.unwrap_or(false),
+    )
+    .await
 }
 
-#[get("/fd/<fd>/<name>")]
+#[get("/fd/<fd>/<name>?<download>")]
 async fn download_file_named(
     db: Connection<FileIndex>,
+    index: &State<FileIndex>,
     fd: FileDescriptor,
     name: String,
-    config: &State<AppConfig<'_>>,
+    download: Option<bool>,
+    backend: &State<Arc<dyn StorageBackend>>,
 ) -> Result<Option<FileDownload>, FileError> {
-    start_file_download(db, fd, Some(name), &config.file_path).await
+    start_file_download(
+        db,
+        index.0.clone(),
+        fd,
+        Some(name),
+        backend.inner().clone(),
+        download.unwrap_or(false),
+    )
+    .await
 }
 
 #[get("/fd/<_>", rank = 2)]
@@ -273,20 +931,60 @@ async fn download_file_invalid_fd() -> FileError {
 }
 
 async fn upload_file(
-    db: Connection<FileIndex>,
+    mut db: Connection<FileIndex>,
     name: Option<String>,
     file: &mut TempFile<'_>,
     addr: IpAddr,
     base_url: &Absolute<'_>,
-    file_path: &Path,
+    backend: &dyn StorageBackend,
+    expiry_secs: Option<i64>,
+    one_time: bool,
+    max_file_size: Option<u64>,
+    max_total_bytes_per_ip: Option<u64>,
 ) -> Result<Option<String>, FileError> {
+    let size = file.len();
+
+    if matches!(max_file_size, Some(max) if size > max) {
+        return Err(FileError::too_large());
+    }
+
+    // Fast, non-authoritative rejection of obviously-over-quota uploads
+    // before we spend time hashing. The authoritative check happens
+    // transactionally alongside the insert in `add_file_with_quota`.
+    if let Some(max_total) = max_total_bytes_per_ip {
+        let used = total_upload_bytes(&mut db, addr).await? as u64;
+        if used.saturating_add(size) > max_total {
+            return Err(FileError::quota_exceeded());
+        }
+    }
+
     let fd: FileDescriptor = Uuid::new_v4().into();
 
-    let path = Path::new(&file_path).join(fd.to_string());
-    file.move_copy_to(path.clone()).await?;
+    let (content_hash, sniff) = hash_file(file).await?;
+    let content_type = sniff_content_type(file.content_type(), &sniff);
 
-    let file = FileInfo::new(fd, name, addr);
-    add_file(db, file).await?;
+    let mut reader = file.open().await?;
+    ensure_blob(&mut db, &content_hash, &mut reader, backend).await?;
+
+    let valid_till = expiry_secs.map(resolve_expiry);
+    let file = FileInfo::new(
+        fd,
+        name,
+        addr,
+        valid_till,
+        content_hash,
+        content_type,
+        one_time,
+        size as i64,
+    );
+    let content_hash = file.content_hash.clone();
+    if let Err(e) = add_file_with_quota(&mut db, file, max_total_bytes_per_ip).await {
+        // The row never landed (quota rejection or otherwise): release the
+        // blob reference `ensure_blob` just took so novel content doesn't
+        // end up an orphaned blob with nothing left to reap it.
+        release_orphaned_blob(&mut db, &content_hash, backend).await?;
+        return Err(e);
+    }
 
     Ok(Some(format!(
         "{}\n",
@@ -294,12 +992,19 @@ async fn upload_file(
     )))
 }
 
-#[post("/raw", format = "application/x-www-form-urlencoded", data = "<file>")]
+#[post(
+    "/raw?<expiry>&<one_time>",
+    format = "application/x-www-form-urlencoded",
+    data = "<file>"
+)]
 async fn upload_file_raw(
     db: Connection<FileIndex>,
     mut file: TempFile<'_>,
     addr: IpAddr,
+    expiry: Option<i64>,
+    one_time: Option<bool>,
     config: &State<AppConfig<'_>>,
+    backend: &State<Arc<dyn StorageBackend>>,
 ) -> Result<Option<String>, FileError> {
     upload_file(
         db,
@@ -307,7 +1012,11 @@ async fn upload_file_raw(
         &mut file,
         addr,
         &config.base_url,
-        &config.file_path,
+        backend.as_ref(),
+        expiry.or(config.default_ttl_secs),
+        one_time.unwrap_or(false),
+        config.max_file_size,
+        config.max_total_bytes_per_ip,
     )
     .await
 }
@@ -321,6 +1030,8 @@ async fn upload_file_raw_invalid() -> FileError {
 struct FileDescriptorForm<'r> {
     file: TempFile<'r>,
     name: Option<String>,
+    expiry: Option<i64>,
+    one_time: Option<bool>,
 }
 
 #[post("/", data = "<form>")]
@@ -329,6 +1040,7 @@ async fn upload_file_form(
     mut form: Form<FileDescriptorForm<'_>>,
     addr: IpAddr,
     config: &State<AppConfig<'_>>,
+    backend: &State<Arc<dyn StorageBackend>>,
 ) -> Result<Option<String>, FileError> {
     upload_file(
         db,
@@ -336,7 +1048,11 @@ async fn upload_file_form(
         &mut form.file,
         addr,
         &config.base_url,
-        &config.file_path,
+        backend.as_ref(),
+        form.expiry.or(config.default_ttl_secs),
+        form.one_time.unwrap_or(false),
+        config.max_file_size,
+        config.max_total_bytes_per_ip,
     )
     .await
 }
@@ -405,4 +1121,6 @@ fn rocket() -> _ {
         .register("/fd", catchers![file_not_found])
         .attach(FileIndex::init())
         .attach(AdHoc::config::<AppConfig>())
+        .attach(storage_fairing())
+        .attach(reaper_fairing())
 }
@@ -0,0 +1,347 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use rocket::async_trait;
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncRead, AsyncReadExt};
+use serde::Deserialize;
+
+/// Errors a [`StorageBackend`] can return. Always mapped to a 500 by
+/// [`crate::FileError::StorageError`] since none of them are the caller's fault.
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    S3(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "storage i/o error: {}", e),
+            Self::S3(e) => write!(f, "s3 error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Where uploaded blobs actually live. `download_file*`, `upload_file*` and the
+/// reaper all go through this instead of touching the filesystem directly, so
+/// devfd can run stateless behind a load balancer with the index in SQLite and
+/// the bytes in object storage.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), StorageError>;
+
+    async fn get(
+        &self,
+        key: &str,
+    ) -> Result<Option<Box<dyn AsyncRead + Send + Unpin>>, StorageError>;
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// The original behaviour: blobs as plain files under `file_path`.
+pub struct LocalFs {
+    file_path: PathBuf,
+}
+
+impl LocalFs {
+    pub fn new(file_path: PathBuf) -> Self {
+        Self { file_path }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalFs {
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), StorageError> {
+        let mut out = File::create(self.file_path.join(key)).await?;
+        rocket::tokio::io::copy(reader, &mut out).await?;
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+    ) -> Result<Option<Box<dyn AsyncRead + Send + Unpin>>, StorageError> {
+        match File::open(self.file_path.join(key)).await {
+            Ok(f) => Ok(Some(Box::new(f))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match rocket::tokio::fs::remove_file(self.file_path.join(key)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Blobs in an S3-compatible bucket, configured via [`crate::AppConfig`].
+pub struct S3 {
+    bucket: String,
+    client: aws_sdk_s3::Client,
+}
+
+impl S3 {
+    pub async fn new(config: &S3Config) -> Self {
+        let mut loader = aws_config::from_env().region(aws_config::Region::new(config.region.clone()));
+        if let Some(endpoint) = &config.endpoint {
+            loader = loader.endpoint_url(endpoint);
+        }
+        let sdk_config = loader
+            .credentials_provider(aws_sdk_s3::config::Credentials::new(
+                &config.access_key,
+                &config.secret_key,
+                None,
+                None,
+                "devfd",
+            ))
+            .load()
+            .await;
+
+        Self {
+            bucket: config.bucket.clone(),
+            client: aws_sdk_s3::Client::new(&sdk_config),
+        }
+    }
+
+    fn is_not_found(e: &aws_sdk_s3::error::SdkError<impl std::error::Error>) -> bool {
+        e.to_string().contains("NoSuchKey")
+    }
+
+    /// Uploads `buf[..filled]` (the part already read by the caller) as part
+    /// 1 of `upload_id`, then keeps reading and uploading further
+    /// `S3_PART_SIZE` chunks from `reader` until it's exhausted. Requires
+    /// `filled > 0`: a multipart upload must have at least one part, which
+    /// is why `put` special-cases an empty `reader` into a plain
+    /// `put_object` instead of calling this.
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        mut buf: Vec<u8>,
+        mut filled: usize,
+    ) -> Result<Vec<aws_sdk_s3::types::CompletedPart>, StorageError> {
+        let mut parts = Vec::new();
+        let mut part_number = 1i32;
+
+        loop {
+            parts.push(
+                self.upload_part(key, upload_id, part_number, &buf[..filled])
+                    .await?,
+            );
+
+            if filled < buf.len() {
+                break;
+            }
+
+            part_number += 1;
+            filled = read_full(reader, &mut buf).await?;
+            if filled == 0 {
+                break;
+            }
+        }
+
+        Ok(parts)
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        data: &[u8],
+    ) -> Result<aws_sdk_s3::types::CompletedPart, StorageError> {
+        let part = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(aws_sdk_s3::primitives::ByteStream::from(data.to_vec()))
+            .send()
+            .await
+            .map_err(|e| StorageError::S3(e.to_string()))?;
+
+        Ok(aws_sdk_s3::types::CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(part.e_tag().map(String::from))
+            .build())
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<aws_sdk_s3::types::CompletedPart>,
+    ) -> Result<(), StorageError> {
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                aws_sdk_s3::types::CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| StorageError::S3(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Multipart parts must be at least 5 MiB (except the last one), so this is
+/// also the most we ever have to hold in memory for a single `put`.
+const S3_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Reads from `reader` until `buf` is full or EOF, returning how much of
+/// `buf` was filled.
+async fn read_full(
+    reader: &mut (dyn AsyncRead + Send + Unpin),
+    buf: &mut [u8],
+) -> Result<usize, io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+#[async_trait]
+impl StorageBackend for S3 {
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> Result<(), StorageError> {
+        // Upload via S3's multipart API in fixed-size chunks instead of
+        // `read_to_end`-ing the whole object into memory first: a single
+        // large upload would otherwise end up buffered twice over (once by
+        // the caller, once here).
+        let mut buf = vec![0u8; S3_PART_SIZE];
+        let filled = read_full(reader, &mut buf).await?;
+
+        if filled == 0 {
+            // A multipart upload needs at least one part and S3 refuses to
+            // complete one with zero parts, but empty files are a normal
+            // case for a file-sharing tool — skip multipart entirely.
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .body(aws_sdk_s3::primitives::ByteStream::from(Vec::new()))
+                .send()
+                .await
+                .map_err(|e| StorageError::S3(e.to_string()))?;
+            return Ok(());
+        }
+
+        let upload_id = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| StorageError::S3(e.to_string()))?
+            .upload_id()
+            .ok_or_else(|| StorageError::S3("multipart upload missing an id".to_string()))?
+            .to_string();
+
+        match self.upload_parts(key, &upload_id, reader, buf, filled).await {
+            Ok(parts) => self.complete_multipart_upload(key, &upload_id, parts).await,
+            Err(e) => {
+                let _ = self
+                    .client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(&upload_id)
+                    .send()
+                    .await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+    ) -> Result<Option<Box<dyn AsyncRead + Send + Unpin>>, StorageError> {
+        match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => Ok(Some(Box::new(output.body.into_async_read()))),
+            Err(e) if Self::is_not_found(&e) => Ok(None),
+            Err(e) => Err(StorageError::S3(e.to_string())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| StorageError::S3(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StorageConfig {
+    LocalFs,
+    S3(S3Config),
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self::LocalFs
+    }
+}